@@ -0,0 +1,7 @@
+// Requires `arc-swap`, `prost`, and a `prost-build` build-dependency to be
+// declared in this crate's `Cargo.toml` (not present in this source tree —
+// see the chunk0-6 commit message for why).
+fn main() {
+    prost_build::compile_protos(&["proto/ip_restriction.proto"], &["proto/"])
+        .expect("failed to compile proto/ip_restriction.proto");
+}
@@ -1,26 +1,381 @@
+mod rate_limiter;
+mod trie;
+
+// Generated from `proto/ip_restriction.proto` by `build.rs`. This is the
+// typed (protobuf) equivalent of `RawFilterConfig`, accepted alongside the
+// existing JSON configuration; see `new_http_filter_config_fn`.
+mod pb {
+    include!(concat!(env!("OUT_DIR"), "/ip_restriction.rs"));
+}
+
+use crate::rate_limiter::RateLimiter;
+use crate::trie::{Ipv4Trie, Ipv6Trie};
+use arc_swap::ArcSwap;
 use envoy_proxy_dynamic_modules_rust_sdk::*;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::collections::HashSet;
+use std::net::IpAddr;
 use std::net::Ipv4Addr;
 use std::net::Ipv6Addr;
 use std::str::FromStr;
 use std::sync::Arc;
+use std::sync::Weak;
+use std::time::Duration;
+
+fn default_dns_refresh_interval_secs() -> u64 {
+    30
+}
 
-// The raw filter config that will be deserialized from the JSON configuration.
-// TODO(wbpcode): To support protobuf based API declaration in the future.
-// TODO(wbpcode): to support ip range in the future.
+// The raw filter config that will be deserialized from the JSON
+// configuration, or converted from the typed `pb::FilterConfig` protobuf
+// message (see `From<pb::FilterConfig> for RawFilterConfig` below).
+//
+// Entries in `allow_addresses`/`deny_addresses` are either a bare IP address
+// (treated as a /32 or /128) or a CIDR range such as `"10.0.0.0/8"` or
+// `"2001:db8::/32"`.
 #[derive(Serialize, Deserialize, Debug)]
 pub struct RawFilterConfig {
     #[serde(default)]
     deny_addresses: HashSet<String>,
     #[serde(default)]
     allow_addresses: HashSet<String>,
+    // When set, the client address is resolved from the `x-forwarded-for`
+    // request header instead of the immediate TCP peer address, which is
+    // necessary when the filter runs behind a load balancer or CDN.
+    #[serde(default)]
+    use_xff: bool,
+    // The number of trusted proxy hops that are expected to have appended
+    // their own address to `x-forwarded-for`. The client address is the
+    // (num_trusted_hops + 1)-th address counted from the right of the
+    // header. Only meaningful when `use_xff` is set.
+    #[serde(default)]
+    num_trusted_hops: u32,
+    // How often configured hostnames are re-resolved in the background.
+    #[serde(default = "default_dns_refresh_interval_secs")]
+    dns_refresh_interval_secs: u64,
+    // Overrides the response sent for every denied request (no resolvable
+    // client address, allow-list miss, deny-list hit, or rate-limit
+    // exhaustion). Defaults to a 403 with a
+    // plain "Request is forbidden." body.
+    #[serde(default)]
+    deny_response: Option<RawDenyResponse>,
+    // When set, enables a per-client-IP token-bucket rate limiter on top of
+    // the allow/deny lists.
+    #[serde(default)]
+    rate_limit: Option<RawRateLimit>,
+    // When set, records the resolved client address and match decision as
+    // dynamic metadata on every request, in addition to the `ip_restriction.*`
+    // counters, which are always emitted.
+    #[serde(default)]
+    dynamic_metadata: Option<RawDynamicMetadata>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RawDenyResponse {
+    #[serde(default = "default_deny_status")]
+    status: u32,
+    #[serde(default)]
+    body: Option<String>,
+    #[serde(default)]
+    headers: Vec<(String, String)>,
+}
+
+fn default_deny_status() -> u32 {
+    403
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RawRateLimit {
+    requests_per_unit: u32,
+    fill_interval_ms: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RawDynamicMetadata {
+    #[serde(default = "default_dynamic_metadata_namespace")]
+    namespace: String,
+}
+
+fn default_dynamic_metadata_namespace() -> String {
+    "envoy.filters.http.dynamic_modules.ip_restriction".to_string()
+}
+
+impl From<pb::FilterConfig> for RawFilterConfig {
+    fn from(pb: pb::FilterConfig) -> Self {
+        Self {
+            deny_addresses: pb.deny_addresses.into_iter().collect(),
+            allow_addresses: pb.allow_addresses.into_iter().collect(),
+            use_xff: pb.use_xff,
+            num_trusted_hops: pb.num_trusted_hops,
+            dns_refresh_interval_secs: if pb.dns_refresh_interval_secs == 0 {
+                default_dns_refresh_interval_secs()
+            } else {
+                pb.dns_refresh_interval_secs
+            },
+            deny_response: pb.deny_response.map(RawDenyResponse::from),
+            rate_limit: pb.rate_limit.map(RawRateLimit::from),
+            dynamic_metadata: pb.dynamic_metadata.map(RawDynamicMetadata::from),
+        }
+    }
+}
+
+impl From<pb::DenyResponse> for RawDenyResponse {
+    fn from(pb: pb::DenyResponse) -> Self {
+        Self {
+            status: if pb.status == 0 {
+                default_deny_status()
+            } else {
+                pb.status
+            },
+            body: (!pb.body.is_empty()).then_some(pb.body),
+            headers: pb.headers.into_iter().map(|h| (h.key, h.value)).collect(),
+        }
+    }
+}
+
+impl From<pb::RateLimit> for RawRateLimit {
+    fn from(pb: pb::RateLimit) -> Self {
+        Self {
+            requests_per_unit: pb.requests_per_unit,
+            fill_interval_ms: pb.fill_interval_ms,
+        }
+    }
+}
+
+impl From<pb::DynamicMetadata> for RawDynamicMetadata {
+    fn from(pb: pb::DynamicMetadata) -> Self {
+        Self {
+            namespace: if pb.namespace.is_empty() {
+                default_dynamic_metadata_namespace()
+            } else {
+                pb.namespace
+            },
+        }
+    }
+}
+
+// The fully resolved denial response, sent whenever a request is rejected.
+#[derive(Debug, Clone)]
+struct DenyResponse {
+    status: u32,
+    body: Vec<u8>,
+    headers: Vec<(String, String)>,
+}
+
+impl Default for DenyResponse {
+    fn default() -> Self {
+        Self {
+            status: 403,
+            body: b"Request is forbidden.".to_vec(),
+            headers: Vec::new(),
+        }
+    }
+}
+
+impl From<RawDenyResponse> for DenyResponse {
+    fn from(raw: RawDenyResponse) -> Self {
+        Self {
+            status: raw.status,
+            body: raw
+                .body
+                .map(String::into_bytes)
+                .unwrap_or_else(|| b"Request is forbidden.".to_vec()),
+            headers: raw.headers,
+        }
+    }
+}
+
+// A parsed set of addresses, CIDR ranges and hostnames to match a client
+// address against.
+//
+// Exact (non-range) entries are kept in a `HashSet` for an O(1) fast path,
+// ranges are kept in per-family prefix tries and matched with a
+// longest-prefix-match descent, and hostnames are periodically resolved in
+// the background into `resolved_hostnames`, which is wrapped in `ArcSwap` so
+// `contains` stays lock-free on the hot path.
+#[derive(Debug, Default)]
+struct AddressSet {
+    exact: HashSet<IpAddr>,
+    cidr_v4: Ipv4Trie,
+    cidr_v6: Ipv6Trie,
+    resolved_hostnames: Arc<ArcSwap<HashSet<IpAddr>>>,
+    configured: bool,
+}
+
+impl AddressSet {
+    fn insert(&mut self, addr: IpAddr, prefix_len: u8) {
+        self.configured = true;
+        match addr {
+            IpAddr::V4(v4) if prefix_len == 32 => {
+                self.exact.insert(IpAddr::V4(v4));
+            }
+            IpAddr::V4(v4) => {
+                self.cidr_v4
+                    .insert((u32::from(v4) as u128) << 96, prefix_len);
+            }
+            IpAddr::V6(v6) if prefix_len == 128 => {
+                self.exact.insert(IpAddr::V6(v6));
+            }
+            IpAddr::V6(v6) => {
+                self.cidr_v6.insert(u128::from(v6), prefix_len);
+            }
+        }
+    }
+
+    // Records that a hostname entry was configured for this set, even though
+    // its resolved addresses are only populated once the background refresh
+    // task completes its first lookup.
+    fn note_hostname(&mut self) {
+        self.configured = true;
+    }
+
+    fn contains(&self, addr: &IpAddr) -> bool {
+        if self.exact.contains(addr) {
+            return true;
+        }
+        if self.resolved_hostnames.load().contains(addr) {
+            return true;
+        }
+        match addr {
+            IpAddr::V4(v4) => self
+                .cidr_v4
+                .longest_prefix_match((u32::from(*v4) as u128) << 96),
+            IpAddr::V6(v6) => self.cidr_v6.longest_prefix_match(u128::from(*v6)),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        !self.configured
+    }
+}
+
+// A minimal hostname syntax check (labels of alphanumerics/hyphens joined by
+// dots). This is intentionally permissive: the actual validity of a name is
+// determined by whether it resolves, not by this check.
+fn is_valid_hostname(entry: &str) -> bool {
+    if entry.is_empty() || entry.len() > 253 {
+        return false;
+    }
+    let labels: Vec<&str> = entry.split('.').collect();
+    if !labels.iter().all(|label| {
+        !label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    }) {
+        return false;
+    }
+    // Reject anything that looks like a failed IP literal (e.g.
+    // "192.168.1.256", where an octet is out of range and so
+    // `Ipv4Addr::from_str` rejected it) rather than silently treating a
+    // typo'd address as a hostname to resolve.
+    if labels
+        .iter()
+        .all(|label| label.chars().all(|c| c.is_ascii_digit()))
+    {
+        return false;
+    }
+    true
+}
+
+// Resolves every hostname in `hostnames` and stores the union of their
+// addresses into `target`, looping forever at `interval`. On a per-hostname
+// resolution failure, that hostname's last known-good addresses are kept
+// instead of being dropped from the union. The loop exits once `target` has
+// no more owners, i.e. the owning `FilterConfigImpl` was dropped.
+async fn refresh_hostnames_loop(
+    hostnames: Vec<String>,
+    target: Weak<ArcSwap<HashSet<IpAddr>>>,
+    interval: Duration,
+) {
+    let mut last_known_good: HashMap<String, HashSet<IpAddr>> = HashMap::new();
+    loop {
+        let Some(target) = target.upgrade() else {
+            return;
+        };
+
+        for hostname in &hostnames {
+            match tokio::net::lookup_host((hostname.as_str(), 0)).await {
+                Ok(addrs) => {
+                    last_known_good.insert(
+                        hostname.clone(),
+                        addrs.map(|a| canonicalize_ip(a.ip())).collect(),
+                    );
+                }
+                Err(err) => {
+                    eprintln!(
+                        "Error resolving hostname {hostname}: {err}, keeping last known-good set"
+                    );
+                }
+            }
+        }
+
+        let resolved: HashSet<IpAddr> = last_known_good.values().flatten().copied().collect();
+        target.store(Arc::new(resolved));
+        drop(target);
+
+        tokio::time::sleep(interval).await;
+    }
+}
+
+// Canonicalizes an IPv4-mapped IPv6 address (e.g. `::ffff:127.0.0.1`) down to
+// its underlying IPv4 address, so it matches a configured `"127.0.0.1"`
+// regardless of which textual form the listener reports the peer as.
+fn canonicalize_ip(addr: IpAddr) -> IpAddr {
+    match addr {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(IpAddr::V6(v6), IpAddr::V4),
+        IpAddr::V4(v4) => IpAddr::V4(v4),
+    }
+}
+
+// Parses a single `allow_addresses`/`deny_addresses` entry into the address
+// and prefix length it denotes, treating a bare IP as a /32 (v4) or /128
+// (v6). Returns `None` if the entry is not a valid address, or the prefix
+// length exceeds the address family's bit width.
+fn parse_address_entry(entry: &str) -> Option<(IpAddr, u8)> {
+    match entry.split_once('/') {
+        Some((addr, prefix_len)) => {
+            let prefix_len: u8 = prefix_len.parse().ok()?;
+            if let Ok(v4) = Ipv4Addr::from_str(addr) {
+                (prefix_len <= 32).then_some((IpAddr::V4(v4), prefix_len))
+            } else if let Ok(v6) = Ipv6Addr::from_str(addr) {
+                if prefix_len > 128 {
+                    return None;
+                }
+                // A prefix of at least /96 falls entirely within the
+                // `::ffff:0:0/96` mapped range, so it can be canonicalized
+                // down to an IPv4 range the same way a bare address is,
+                // below.
+                match canonicalize_ip(IpAddr::V6(v6)) {
+                    IpAddr::V4(v4) if prefix_len >= 96 => Some((IpAddr::V4(v4), prefix_len - 96)),
+                    _ => Some((IpAddr::V6(v6), prefix_len)),
+                }
+            } else {
+                None
+            }
+        }
+        None => {
+            if let Ok(v4) = Ipv4Addr::from_str(entry) {
+                Some((IpAddr::V4(v4), 32))
+            } else if let Ok(v6) = Ipv6Addr::from_str(entry) {
+                match canonicalize_ip(IpAddr::V6(v6)) {
+                    addr @ IpAddr::V4(_) => Some((addr, 32)),
+                    addr @ IpAddr::V6(_) => Some((addr, 128)),
+                }
+            } else {
+                None
+            }
+        }
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct FilterConfigImpl {
-    deny_addresses_exact: HashSet<String>,
-    allow_addresses_exact: HashSet<String>,
+    deny_addresses: AddressSet,
+    allow_addresses: AddressSet,
+    use_xff: bool,
+    num_trusted_hops: u32,
+    deny_response: DenyResponse,
+    rate_limiter: Option<RateLimiter>,
+    dynamic_metadata_namespace: Option<String>,
 }
 
 // This implements the [`envoy_proxy_dynamic_modules_rust_sdk::HttpFilterConfig`] trait.
@@ -44,7 +399,27 @@ impl FilterConfig {
                 return None;
             }
         };
+        Self::from_raw(filter_config)
+    }
+
+    // Builds a [`FilterConfig`] from a binary-encoded `pb::FilterConfig`, the
+    // typed (protobuf) equivalent of the JSON configuration accepted by
+    // [`FilterConfig::new`]. Used when the filter is configured via a typed
+    // `typed_config` rather than a plain JSON string.
+    pub fn from_proto(filter_config: &[u8]) -> Option<Self> {
+        let filter_config: pb::FilterConfig = match prost::Message::decode(filter_config) {
+            Ok(cfg) => cfg,
+            Err(err) => {
+                eprintln!("Error parsing protobuf filter config: {err}");
+                return None;
+            }
+        };
+        Self::from_raw(filter_config.into())
+    }
 
+    // Shared construction path for both the JSON and protobuf configuration
+    // encodings.
+    fn from_raw(filter_config: RawFilterConfig) -> Option<Self> {
         // One and only one of deny_addresses and allow_addresses should be set.
         if filter_config.deny_addresses.is_empty() == filter_config.allow_addresses.is_empty() {
             eprintln!(
@@ -54,29 +429,96 @@ impl FilterConfig {
             return None;
         }
 
-        let mut deny_addresses_exact = HashSet::new();
-        let mut allow_addresses_exact = HashSet::new();
+        let mut allow_addresses = AddressSet::default();
+        let mut deny_addresses = AddressSet::default();
+        let mut allow_hostnames = Vec::new();
+        let mut deny_hostnames = Vec::new();
 
-        // Validate every ip in the set is a valid IPv4 address or IPv6 address.
-        for ip in &filter_config.allow_addresses {
-            if Ipv4Addr::from_str(ip).is_err() && Ipv6Addr::from_str(ip).is_err() {
-                eprintln!("Error parsing ip in allow_addresses: {ip}");
-                return None;
+        // Every entry is either a valid IPv4/IPv6 address, a CIDR range, or a
+        // DNS hostname. Addresses/ranges are indexed for fast lookup in
+        // `on_request_headers`; hostnames are queued for background
+        // resolution below.
+        for entry in &filter_config.allow_addresses {
+            match parse_address_entry(entry) {
+                Some((addr, prefix_len)) => allow_addresses.insert(addr, prefix_len),
+                None if is_valid_hostname(entry) => {
+                    allow_addresses.note_hostname();
+                    allow_hostnames.push(entry.clone());
+                }
+                None => {
+                    eprintln!("Error parsing entry in allow_addresses: {entry}");
+                    return None;
+                }
             }
-            allow_addresses_exact.insert(ip.clone());
         }
-        for ip in &filter_config.deny_addresses {
-            if Ipv4Addr::from_str(ip).is_err() && Ipv6Addr::from_str(ip).is_err() {
-                eprintln!("Error parsing ip in deny_addresses: {ip}");
-                return None;
+        for entry in &filter_config.deny_addresses {
+            match parse_address_entry(entry) {
+                Some((addr, prefix_len)) => deny_addresses.insert(addr, prefix_len),
+                None if is_valid_hostname(entry) => {
+                    deny_addresses.note_hostname();
+                    deny_hostnames.push(entry.clone());
+                }
+                None => {
+                    eprintln!("Error parsing entry in deny_addresses: {entry}");
+                    return None;
+                }
+            }
+        }
+
+        // Spawning requires a Tokio runtime, which the host process (Envoy)
+        // is expected to provide. Skip gracefully rather than panicking if
+        // one isn't available, e.g. when running plain unit tests.
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            // A configured value of 0 would turn this into a tight
+            // re-resolution loop; fall back to the default like the
+            // protobuf path does (see `From<pb::FilterConfig>` above).
+            let dns_refresh_interval_secs = if filter_config.dns_refresh_interval_secs == 0 {
+                default_dns_refresh_interval_secs()
+            } else {
+                filter_config.dns_refresh_interval_secs
+            };
+            let refresh_interval = Duration::from_secs(dns_refresh_interval_secs);
+            if !allow_hostnames.is_empty() {
+                let target = Arc::downgrade(&allow_addresses.resolved_hostnames);
+                handle.spawn(refresh_hostnames_loop(
+                    allow_hostnames,
+                    target,
+                    refresh_interval,
+                ));
+            }
+            if !deny_hostnames.is_empty() {
+                let target = Arc::downgrade(&deny_addresses.resolved_hostnames);
+                handle.spawn(refresh_hostnames_loop(
+                    deny_hostnames,
+                    target,
+                    refresh_interval,
+                ));
             }
-            deny_addresses_exact.insert(ip.clone());
+        } else if !allow_hostnames.is_empty() || !deny_hostnames.is_empty() {
+            eprintln!("No Tokio runtime available: configured hostnames will not be resolved");
         }
 
+        let deny_response = filter_config
+            .deny_response
+            .map(DenyResponse::from)
+            .unwrap_or_default();
+        let rate_limiter = filter_config.rate_limit.map(|rate_limit| {
+            RateLimiter::new(
+                rate_limit.requests_per_unit,
+                Duration::from_millis(rate_limit.fill_interval_ms),
+            )
+        });
+        let dynamic_metadata_namespace = filter_config.dynamic_metadata.map(|cfg| cfg.namespace);
+
         Some(FilterConfig {
             config: Arc::new(FilterConfigImpl {
-                deny_addresses_exact,
-                allow_addresses_exact,
+                deny_addresses,
+                allow_addresses,
+                use_xff: filter_config.use_xff,
+                num_trusted_hops: filter_config.num_trusted_hops,
+                deny_response,
+                rate_limiter,
+                dynamic_metadata_namespace,
             }),
         })
     }
@@ -99,75 +541,226 @@ pub struct Filter {
     filter_config: FilterConfig,
 }
 
-/// This implements the [`envoy_proxy_dynamic_modules_rust_sdk::HttpFilter`] trait.
-impl<EHF: EnvoyHttpFilter> HttpFilter<EHF> for Filter {
-    fn on_request_headers(
-        &mut self,
+// The outcome of matching a request's resolved client address against the
+// allow/deny lists and rate limiter. Each variant maps to an
+// `ip_restriction.*` counter name and to a dynamic metadata "decision" value;
+// the counter name is the decision value prefixed with `ip_restriction.`.
+#[derive(Debug, Clone, Copy)]
+enum Decision {
+    Allowed,
+    DeniedNoAddress,
+    DeniedAllowlistMiss,
+    DeniedDenylistHit,
+    DeniedRateLimited,
+}
+
+impl Decision {
+    fn counter_name(self) -> &'static str {
+        match self {
+            Decision::Allowed => "ip_restriction.allowed",
+            Decision::DeniedNoAddress => "ip_restriction.denied_no_address",
+            Decision::DeniedAllowlistMiss => "ip_restriction.denied_allowlist_miss",
+            Decision::DeniedDenylistHit => "ip_restriction.denied_denylist_hit",
+            Decision::DeniedRateLimited => "ip_restriction.denied_rate_limited",
+        }
+    }
+
+    fn metadata_value(self) -> &'static str {
+        match self {
+            Decision::Allowed => "allowed",
+            Decision::DeniedNoAddress => "denied_no_address",
+            Decision::DeniedAllowlistMiss => "denied_allowlist_miss",
+            Decision::DeniedDenylistHit => "denied_denylist_hit",
+            Decision::DeniedRateLimited => "denied_rate_limited",
+        }
+    }
+}
+
+impl Filter {
+    // Resolves the client address to match against the allow/deny lists.
+    //
+    // If `use_xff` is configured, the address is read from the
+    // `x-forwarded-for` request header, counting `num_trusted_hops + 1`
+    // addresses from the right. Otherwise (or if the header is absent or has
+    // fewer entries than expected) the immediate TCP peer address is used.
+    //
+    // Returns `Ok(None)` when no address could be determined at all, and
+    // `Err(())` when a present `x-forwarded-for` entry fails to parse as an
+    // IP address.
+    fn resolve_client_address<EHF: EnvoyHttpFilter>(
+        &self,
         envoy_filter: &mut EHF,
-        _end_stream: bool,
+    ) -> Result<Option<IpAddr>, ()> {
+        if !self.filter_config.config.use_xff {
+            return Ok(resolve_source_address(envoy_filter));
+        }
+        match resolve_xff_address(envoy_filter, self.filter_config.config.num_trusted_hops)? {
+            Some(addr) => Ok(Some(addr)),
+            None => Ok(resolve_source_address(envoy_filter)),
+        }
+    }
+
+    // Records `decision`, then sends the configured denial response and
+    // returns the `StopIteration` status that callers should return from
+    // `on_request_headers`.
+    fn deny<EHF: EnvoyHttpFilter>(
+        &self,
+        envoy_filter: &mut EHF,
+        decision: Decision,
+        client_addr: Option<IpAddr>,
     ) -> abi::envoy_dynamic_module_type_on_http_filter_request_headers_status {
-        let downstream_addr = envoy_filter
-            .get_attribute_string(abi::envoy_dynamic_module_type_attribute_id::SourceAddress);
-        let downstream_port =
-            envoy_filter.get_attribute_int(abi::envoy_dynamic_module_type_attribute_id::SourcePort);
-
-        if downstream_addr.is_none() || downstream_port.is_none() {
-            envoy_filter.send_response(
-                403,
-                vec![],
-                Some(b"No remote address and request is forbidden."),
+        self.record_decision(envoy_filter, decision, client_addr);
+        let deny_response = &self.filter_config.config.deny_response;
+        envoy_filter.send_response(
+            deny_response.status,
+            deny_response.headers.clone(),
+            Some(&deny_response.body),
+        );
+        abi::envoy_dynamic_module_type_on_http_filter_request_headers_status::StopIteration
+    }
+
+    // Increments the `ip_restriction.*` counter for `decision` and, if a
+    // dynamic metadata namespace is configured, records the resolved client
+    // address and decision under it so downstream filters, access logs, and
+    // RBAC can consume them.
+    fn record_decision<EHF: EnvoyHttpFilter>(
+        &self,
+        envoy_filter: &mut EHF,
+        decision: Decision,
+        client_addr: Option<IpAddr>,
+    ) {
+        envoy_filter.increment_counter(decision.counter_name(), 1);
+        if let Some(namespace) = &self.filter_config.config.dynamic_metadata_namespace {
+            let client_addr = client_addr.map(|addr| addr.to_string()).unwrap_or_default();
+            envoy_filter.set_dynamic_metadata_string(namespace, "client_address", &client_addr);
+            envoy_filter.set_dynamic_metadata_string(
+                namespace,
+                "decision",
+                decision.metadata_value(),
             );
-            return abi::envoy_dynamic_module_type_on_http_filter_request_headers_status::StopIteration;
         }
+    }
+}
 
-        let mut downstream_addr_str = String::new();
+// Resolves the immediate TCP peer address from the `SourceAddress` and
+// `SourcePort` attributes, stripping the port suffix.
+fn resolve_source_address<EHF: EnvoyHttpFilter>(envoy_filter: &mut EHF) -> Option<IpAddr> {
+    let downstream_addr = envoy_filter
+        .get_attribute_string(abi::envoy_dynamic_module_type_attribute_id::SourceAddress);
+    let downstream_port =
+        envoy_filter.get_attribute_int(abi::envoy_dynamic_module_type_attribute_id::SourcePort);
+    let (downstream_addr, downstream_port) = (downstream_addr?, downstream_port?);
 
-        let address_buffer = downstream_addr.unwrap();
-        let downstream_addr_slice = address_buffer.as_slice();
+    // Strip the port from the downstream addr.
+    let downstream_addr_slice = downstream_addr.as_slice();
+    let downstream_addr_slice = &downstream_addr_slice
+        [0..downstream_addr_slice.len() - downstream_port.to_string().len() - 1];
 
-        if downstream_port.is_none() {
-            // Covert the slice of downstream addr to string.
-            unsafe {
-                downstream_addr_str
-                    .as_mut_vec()
-                    .extend_from_slice(downstream_addr_slice);
-            }
-        } else {
-            // Strip the port from the downstream addr.
-            let downstream_addr_slice = &downstream_addr_slice
-                [0..downstream_addr_slice.len() - downstream_port.unwrap().to_string().len() - 1];
-
-            unsafe {
-                downstream_addr_str
-                    .as_mut_vec()
-                    .extend_from_slice(downstream_addr_slice);
-            }
+    let mut downstream_addr_str = String::new();
+    unsafe {
+        downstream_addr_str
+            .as_mut_vec()
+            .extend_from_slice(downstream_addr_slice);
+    }
+
+    IpAddr::from_str(&downstream_addr_str)
+        .ok()
+        .map(canonicalize_ip)
+}
+
+// Resolves the trusted client address from the `x-forwarded-for` request
+// header: splits it on commas and picks the `num_trusted_hops + 1`-th
+// address counted from the right. Returns `Ok(None)` if the header is absent
+// or has fewer entries than expected, so the caller can fall back to the TCP
+// peer address. Returns `Err(())` if any entry fails to parse as an IP
+// address.
+fn resolve_xff_address<EHF: EnvoyHttpFilter>(
+    envoy_filter: &mut EHF,
+    num_trusted_hops: u32,
+) -> Result<Option<IpAddr>, ()> {
+    let Some(header) = envoy_filter.get_request_header_value("x-forwarded-for") else {
+        return Ok(None);
+    };
+
+    // Unlike `SourceAddress` (Envoy-derived), this header is raw, attacker-
+    // controlled client input, so it must be UTF-8 validated rather than
+    // blindly copied into a `String`.
+    let Ok(header_str) = std::str::from_utf8(header.as_slice()) else {
+        return Err(());
+    };
+
+    let mut hops = Vec::new();
+    for token in header_str.split(',') {
+        match IpAddr::from_str(token.trim()) {
+            Ok(addr) => hops.push(canonicalize_ip(addr)),
+            Err(_) => return Err(()),
         }
+    }
+
+    let index = hops.len().checked_sub(1 + num_trusted_hops as usize);
+    Ok(index.map(|i| hops[i]))
+}
+
+/// This implements the [`envoy_proxy_dynamic_modules_rust_sdk::HttpFilter`] trait.
+impl<EHF: EnvoyHttpFilter> HttpFilter<EHF> for Filter {
+    fn on_request_headers(
+        &mut self,
+        envoy_filter: &mut EHF,
+        _end_stream: bool,
+    ) -> abi::envoy_dynamic_module_type_on_http_filter_request_headers_status {
+        let downstream_addr = match self.resolve_client_address(envoy_filter) {
+            Ok(Some(addr)) => addr,
+            Ok(None) | Err(()) => {
+                return self.deny(envoy_filter, Decision::DeniedNoAddress, None);
+            }
+        };
 
         // Check if the downstream addr is in the allowed list.
-        if !self.filter_config.config.allow_addresses_exact.is_empty()
+        if !self.filter_config.config.allow_addresses.is_empty()
             && !self
                 .filter_config
                 .config
-                .allow_addresses_exact
-                .contains(&downstream_addr_str)
+                .allow_addresses
+                .contains(&downstream_addr)
         {
-            envoy_filter.send_response(403, vec![], Some(b"Request is forbidden."));
-            return abi::envoy_dynamic_module_type_on_http_filter_request_headers_status::StopIteration;
+            return self.deny(
+                envoy_filter,
+                Decision::DeniedAllowlistMiss,
+                Some(downstream_addr),
+            );
         }
 
         // Check if the downstream addr is in the denied list.
-        if !self.filter_config.config.deny_addresses_exact.is_empty()
+        if !self.filter_config.config.deny_addresses.is_empty()
             && self
                 .filter_config
                 .config
-                .deny_addresses_exact
-                .contains(&downstream_addr_str)
+                .deny_addresses
+                .contains(&downstream_addr)
         {
-            envoy_filter.send_response(403, vec![], Some(b"Request is forbidden."));
-            return abi::envoy_dynamic_module_type_on_http_filter_request_headers_status::StopIteration;
+            return self.deny(
+                envoy_filter,
+                Decision::DeniedDenylistHit,
+                Some(downstream_addr),
+            );
+        }
+
+        // Check the per-client-IP rate limit, if configured.
+        let rate_limit_exceeded = self
+            .filter_config
+            .config
+            .rate_limiter
+            .as_ref()
+            .is_some_and(|rate_limiter| !rate_limiter.allow(downstream_addr));
+        if rate_limit_exceeded {
+            return self.deny(
+                envoy_filter,
+                Decision::DeniedRateLimited,
+                Some(downstream_addr),
+            );
         }
 
+        self.record_decision(envoy_filter, Decision::Allowed, Some(downstream_addr));
         abi::envoy_dynamic_module_type_on_http_filter_request_headers_status::Continue
     }
 }
@@ -195,10 +788,18 @@ fn new_http_filter_config_fn<EC: EnvoyHttpFilterConfig, EHF: EnvoyHttpFilter>(
     filter_name: &str,
     filter_config: &[u8],
 ) -> Option<Box<dyn HttpFilterConfig<EC, EHF>>> {
-    let filter_config = std::str::from_utf8(filter_config).unwrap();
     match filter_name {
-        "ip_restriction" => FilterConfig::new(filter_config)
-            .map(|config| Box::new(config) as Box<dyn HttpFilterConfig<EC, EHF>>),
+        "ip_restriction" => {
+            // Accept either a typed (binary protobuf) `typed_config` or a
+            // plain JSON string: try decoding as protobuf first, falling
+            // back to JSON since that's what every filter config predates
+            // this change used.
+            let config = FilterConfig::from_proto(filter_config).or_else(|| {
+                let filter_config = std::str::from_utf8(filter_config).ok()?;
+                FilterConfig::new(filter_config)
+            });
+            config.map(|config| Box::new(config) as Box<dyn HttpFilterConfig<EC, EHF>>)
+        }
         _ => panic!("Unknown filter name: {filter_name}"),
     }
 }
@@ -293,6 +894,10 @@ mod tests {
             .expect_send_response()
             .times(1)
             .returning(|code, _, _| assert!(code == 403));
+        mock_envoy_filter
+            .expect_increment_counter()
+            .times(1)
+            .returning(|_, _| {});
 
         assert_eq!(
             filter.on_request_headers(&mut mock_envoy_filter, true),
@@ -327,6 +932,10 @@ mod tests {
             .expect_get_attribute_int()
             .times(1)
             .returning(|_| Some(80));
+        mock_envoy_filter
+            .expect_increment_counter()
+            .times(1)
+            .returning(|_, _| {});
 
         assert_eq!(
             filter.on_request_headers(&mut mock_envoy_filter, true),
@@ -345,6 +954,10 @@ mod tests {
             .expect_send_response()
             .times(1)
             .returning(|code, _, _| assert!(code == 403));
+        mock_envoy_filter
+            .expect_increment_counter()
+            .times(1)
+            .returning(|_, _| {});
 
         assert_eq!(
             filter.on_request_headers(&mut mock_envoy_filter, true),
@@ -382,6 +995,10 @@ mod tests {
             .expect_send_response()
             .times(1)
             .returning(|code, _, _| assert!(code == 403));
+        mock_envoy_filter
+            .expect_increment_counter()
+            .times(1)
+            .returning(|_, _| {});
 
         assert_eq!(
             filter.on_request_headers(&mut mock_envoy_filter, true),
@@ -396,10 +1013,648 @@ mod tests {
             .expect_get_attribute_int()
             .times(1)
             .returning(|_| Some(80));
+        mock_envoy_filter
+            .expect_increment_counter()
+            .times(1)
+            .returning(|_, _| {});
+
+        assert_eq!(
+            filter.on_request_headers(&mut mock_envoy_filter, true),
+            abi::envoy_dynamic_module_type_on_http_filter_request_headers_status::Continue
+        );
+    }
+
+    #[test]
+    fn test_new_filter_config_invalid_cidr() {
+        let filter_config = FilterConfig::new(
+            r#"{
+        "allow_addresses": [
+          "10.0.0.0/33"
+        ]
+      }"#,
+        );
+        assert!(filter_config.is_none()); // Prefix length out of range for IPv4.
+    }
+
+    #[test]
+    fn test_filter_with_denied_cidr_range() {
+        let filter_config = FilterConfig::new(
+            r#"{
+        "deny_addresses": [
+          "10.0.0.0/8",
+          "2001:db8::/32"
+        ]
+      }"#,
+        );
+        assert!(filter_config.is_some());
+
+        let mut filter = Filter {
+            filter_config: filter_config.unwrap(),
+        };
+
+        let mut mock_envoy_filter =
+            envoy_proxy_dynamic_modules_rust_sdk::MockEnvoyHttpFilter::new();
+
+        // 10.1.2.3 falls within the denied 10.0.0.0/8 range.
+        mock_envoy_filter
+            .expect_get_attribute_string()
+            .times(1)
+            .returning(|_| Some(EnvoyBuffer::new("10.1.2.3:80")));
+        mock_envoy_filter
+            .expect_get_attribute_int()
+            .times(1)
+            .returning(|_| Some(80));
+        mock_envoy_filter
+            .expect_send_response()
+            .times(1)
+            .returning(|code, _, _| assert!(code == 403));
+        mock_envoy_filter
+            .expect_increment_counter()
+            .times(1)
+            .returning(|_, _| {});
+
+        assert_eq!(
+            filter.on_request_headers(&mut mock_envoy_filter, true),
+            abi::envoy_dynamic_module_type_on_http_filter_request_headers_status::StopIteration
+        );
+
+        // 11.0.0.1 is outside the denied range.
+        mock_envoy_filter
+            .expect_get_attribute_string()
+            .times(1)
+            .returning(|_| Some(EnvoyBuffer::new("11.0.0.1:80")));
+        mock_envoy_filter
+            .expect_get_attribute_int()
+            .times(1)
+            .returning(|_| Some(80));
+        mock_envoy_filter
+            .expect_increment_counter()
+            .times(1)
+            .returning(|_, _| {});
 
         assert_eq!(
             filter.on_request_headers(&mut mock_envoy_filter, true),
             abi::envoy_dynamic_module_type_on_http_filter_request_headers_status::Continue
         );
     }
+
+    #[test]
+    fn test_address_set_matches_ipv6_cidr_range() {
+        let mut deny = AddressSet::default();
+        let (addr, prefix_len) = parse_address_entry("2001:db8::/32").unwrap();
+        deny.insert(addr, prefix_len);
+
+        assert!(deny.contains(&IpAddr::V6(Ipv6Addr::from_str("2001:db8::1").unwrap())));
+        assert!(!deny.contains(&IpAddr::V6(Ipv6Addr::from_str("2001:db9::1").unwrap())));
+    }
+
+    #[test]
+    fn test_filter_resolves_client_from_xff_with_trusted_hops() {
+        let filter_config = FilterConfig::new(
+            r#"{
+        "deny_addresses": [
+          "203.0.113.1"
+        ],
+        "use_xff": true,
+        "num_trusted_hops": 2
+      }"#,
+        );
+        assert!(filter_config.is_some());
+
+        let mut filter = Filter {
+            filter_config: filter_config.unwrap(),
+        };
+
+        let mut mock_envoy_filter =
+            envoy_proxy_dynamic_modules_rust_sdk::MockEnvoyHttpFilter::new();
+
+        // The header has two hops after the client: our own proxy, plus one
+        // more trusted hop, so the client address is the leftmost entry.
+        mock_envoy_filter
+            .expect_get_request_header_value()
+            .times(1)
+            .returning(|_| Some(EnvoyBuffer::new("203.0.113.1, 198.51.100.7, 10.0.0.1")));
+        mock_envoy_filter
+            .expect_send_response()
+            .times(1)
+            .returning(|code, _, _| assert!(code == 403));
+        mock_envoy_filter
+            .expect_increment_counter()
+            .times(1)
+            .returning(|_, _| {});
+
+        assert_eq!(
+            filter.on_request_headers(&mut mock_envoy_filter, true),
+            abi::envoy_dynamic_module_type_on_http_filter_request_headers_status::StopIteration
+        );
+    }
+
+    #[test]
+    fn test_filter_falls_back_to_source_address_when_xff_too_short() {
+        let filter_config = FilterConfig::new(
+            r#"{
+        "deny_addresses": [
+          "192.168.1.1"
+        ],
+        "use_xff": true,
+        "num_trusted_hops": 3
+      }"#,
+        );
+        assert!(filter_config.is_some());
+
+        let mut filter = Filter {
+            filter_config: filter_config.unwrap(),
+        };
+
+        let mut mock_envoy_filter =
+            envoy_proxy_dynamic_modules_rust_sdk::MockEnvoyHttpFilter::new();
+
+        // Only two hops present, fewer than num_trusted_hops + 1, so the
+        // filter should fall back to the TCP peer address.
+        mock_envoy_filter
+            .expect_get_request_header_value()
+            .times(1)
+            .returning(|_| Some(EnvoyBuffer::new("198.51.100.7, 10.0.0.1")));
+        mock_envoy_filter
+            .expect_get_attribute_string()
+            .times(1)
+            .returning(|_| Some(EnvoyBuffer::new("192.168.1.1:80")));
+        mock_envoy_filter
+            .expect_get_attribute_int()
+            .times(1)
+            .returning(|_| Some(80));
+        mock_envoy_filter
+            .expect_send_response()
+            .times(1)
+            .returning(|code, _, _| assert!(code == 403));
+        mock_envoy_filter
+            .expect_increment_counter()
+            .times(1)
+            .returning(|_, _| {});
+
+        assert_eq!(
+            filter.on_request_headers(&mut mock_envoy_filter, true),
+            abi::envoy_dynamic_module_type_on_http_filter_request_headers_status::StopIteration
+        );
+    }
+
+    #[test]
+    fn test_filter_denies_malformed_xff_entry() {
+        let filter_config = FilterConfig::new(
+            r#"{
+        "deny_addresses": [
+          "192.168.1.1"
+        ],
+        "use_xff": true
+      }"#,
+        );
+        assert!(filter_config.is_some());
+
+        let mut filter = Filter {
+            filter_config: filter_config.unwrap(),
+        };
+
+        let mut mock_envoy_filter =
+            envoy_proxy_dynamic_modules_rust_sdk::MockEnvoyHttpFilter::new();
+
+        mock_envoy_filter
+            .expect_get_request_header_value()
+            .times(1)
+            .returning(|_| Some(EnvoyBuffer::new("not-an-ip")));
+        mock_envoy_filter
+            .expect_send_response()
+            .times(1)
+            .returning(|code, _, _| assert!(code == 403));
+        mock_envoy_filter
+            .expect_increment_counter()
+            .times(1)
+            .returning(|_, _| {});
+
+        assert_eq!(
+            filter.on_request_headers(&mut mock_envoy_filter, true),
+            abi::envoy_dynamic_module_type_on_http_filter_request_headers_status::StopIteration
+        );
+    }
+
+    #[test]
+    fn test_is_valid_hostname() {
+        assert!(is_valid_hostname("api.example.com"));
+        assert!(is_valid_hostname("localhost"));
+        assert!(!is_valid_hostname(""));
+        assert!(!is_valid_hostname("api..example.com"));
+        assert!(!is_valid_hostname("api.example.com/"));
+        assert!(!is_valid_hostname("192.168.1.256"));
+        assert!(!is_valid_hostname("10.0.0.999"));
+    }
+
+    #[test]
+    fn test_new_filter_config_accepts_hostname() {
+        let filter_config = FilterConfig::new(
+            r#"{
+        "deny_addresses": [
+          "api.example.com"
+        ]
+      }"#,
+        );
+        assert!(filter_config.is_some());
+    }
+
+    #[test]
+    fn test_new_filter_config_rejects_out_of_range_ip_literal() {
+        let filter_config = FilterConfig::new(
+            r#"{
+        "deny_addresses": [
+          "192.168.1.256"
+        ]
+      }"#,
+        );
+        assert!(filter_config.is_none());
+    }
+
+    #[test]
+    fn test_address_set_matches_resolved_hostname() {
+        let mut deny = AddressSet::default();
+        deny.note_hostname();
+        let resolved: HashSet<IpAddr> = [IpAddr::V4(Ipv4Addr::new(93, 184, 216, 34))]
+            .into_iter()
+            .collect();
+        deny.resolved_hostnames.store(Arc::new(resolved));
+
+        assert!(!deny.is_empty());
+        assert!(deny.contains(&IpAddr::V4(Ipv4Addr::new(93, 184, 216, 34))));
+        assert!(!deny.contains(&IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1))));
+    }
+
+    #[test]
+    fn test_new_filter_config_canonicalizes_ipv4_mapped_entry() {
+        let filter_config = FilterConfig::new(
+            r#"{
+        "allow_addresses": [
+          "::ffff:192.168.1.1"
+        ]
+      }"#,
+        );
+        assert!(filter_config.is_some());
+
+        // The entry should have been folded into the plain IPv4 exact set.
+        assert!(filter_config
+            .unwrap()
+            .config
+            .allow_addresses
+            .contains(&IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1))));
+    }
+
+    #[test]
+    fn test_filter_matches_ipv4_mapped_peer_in_allow_mode() {
+        let filter_config = FilterConfig::new(
+            r#"{
+        "allow_addresses": [
+          "192.168.1.1"
+        ]
+      }"#,
+        );
+        assert!(filter_config.is_some());
+
+        let mut filter = Filter {
+            filter_config: filter_config.unwrap(),
+        };
+
+        let mut mock_envoy_filter =
+            envoy_proxy_dynamic_modules_rust_sdk::MockEnvoyHttpFilter::new();
+
+        mock_envoy_filter
+            .expect_get_attribute_string()
+            .times(1)
+            .returning(|_| Some(EnvoyBuffer::new("::ffff:192.168.1.1:80")));
+        mock_envoy_filter
+            .expect_get_attribute_int()
+            .times(1)
+            .returning(|_| Some(80));
+        mock_envoy_filter
+            .expect_increment_counter()
+            .times(1)
+            .returning(|_, _| {});
+
+        assert_eq!(
+            filter.on_request_headers(&mut mock_envoy_filter, true),
+            abi::envoy_dynamic_module_type_on_http_filter_request_headers_status::Continue
+        );
+    }
+
+    #[test]
+    fn test_filter_matches_ipv4_mapped_peer_in_deny_mode() {
+        let filter_config = FilterConfig::new(
+            r#"{
+        "deny_addresses": [
+          "192.168.1.1"
+        ]
+      }"#,
+        );
+        assert!(filter_config.is_some());
+
+        let mut filter = Filter {
+            filter_config: filter_config.unwrap(),
+        };
+
+        let mut mock_envoy_filter =
+            envoy_proxy_dynamic_modules_rust_sdk::MockEnvoyHttpFilter::new();
+
+        mock_envoy_filter
+            .expect_get_attribute_string()
+            .times(1)
+            .returning(|_| Some(EnvoyBuffer::new("::ffff:192.168.1.1:80")));
+        mock_envoy_filter
+            .expect_get_attribute_int()
+            .times(1)
+            .returning(|_| Some(80));
+        mock_envoy_filter
+            .expect_send_response()
+            .times(1)
+            .returning(|code, _, _| assert!(code == 403));
+        mock_envoy_filter
+            .expect_increment_counter()
+            .times(1)
+            .returning(|_, _| {});
+
+        assert_eq!(
+            filter.on_request_headers(&mut mock_envoy_filter, true),
+            abi::envoy_dynamic_module_type_on_http_filter_request_headers_status::StopIteration
+        );
+    }
+
+    #[test]
+    fn test_filter_uses_custom_deny_response() {
+        let filter_config = FilterConfig::new(
+            r#"{
+        "deny_addresses": [
+          "192.168.1.1"
+        ],
+        "deny_response": {
+          "status": 451,
+          "body": "blocked by policy",
+          "headers": [["x-blocked-by", "ip_restriction"]]
+        }
+      }"#,
+        );
+        assert!(filter_config.is_some());
+
+        let mut filter = Filter {
+            filter_config: filter_config.unwrap(),
+        };
+
+        let mut mock_envoy_filter =
+            envoy_proxy_dynamic_modules_rust_sdk::MockEnvoyHttpFilter::new();
+
+        mock_envoy_filter
+            .expect_get_attribute_string()
+            .times(1)
+            .returning(|_| Some(EnvoyBuffer::new("192.168.1.1:80")));
+        mock_envoy_filter
+            .expect_get_attribute_int()
+            .times(1)
+            .returning(|_| Some(80));
+        mock_envoy_filter
+            .expect_send_response()
+            .times(1)
+            .returning(|code, headers, body| {
+                assert_eq!(code, 451);
+                assert_eq!(body, Some(b"blocked by policy".as_slice()));
+                assert_eq!(
+                    headers,
+                    vec![("x-blocked-by".to_string(), "ip_restriction".to_string())]
+                );
+            });
+        mock_envoy_filter
+            .expect_increment_counter()
+            .times(1)
+            .returning(|_, _| {});
+
+        assert_eq!(
+            filter.on_request_headers(&mut mock_envoy_filter, true),
+            abi::envoy_dynamic_module_type_on_http_filter_request_headers_status::StopIteration
+        );
+    }
+
+    #[test]
+    fn test_filter_rate_limits_after_allow_list_passes() {
+        let filter_config = FilterConfig::new(
+            r#"{
+        "allow_addresses": [
+          "127.0.0.1"
+        ],
+        "rate_limit": {
+          "requests_per_unit": 1,
+          "fill_interval_ms": 60000
+        }
+      }"#,
+        );
+        assert!(filter_config.is_some());
+
+        let mut filter = Filter {
+            filter_config: filter_config.unwrap(),
+        };
+
+        let mut mock_envoy_filter =
+            envoy_proxy_dynamic_modules_rust_sdk::MockEnvoyHttpFilter::new();
+
+        mock_envoy_filter
+            .expect_get_attribute_string()
+            .times(1)
+            .returning(|_| Some(EnvoyBuffer::new("127.0.0.1:80")));
+        mock_envoy_filter
+            .expect_get_attribute_int()
+            .times(1)
+            .returning(|_| Some(80));
+        mock_envoy_filter
+            .expect_increment_counter()
+            .times(1)
+            .returning(|_, _| {});
+
+        assert_eq!(
+            filter.on_request_headers(&mut mock_envoy_filter, true),
+            abi::envoy_dynamic_module_type_on_http_filter_request_headers_status::Continue
+        );
+
+        // The single token has been consumed; the next request from the same
+        // IP is rate limited even though it passes the allow list.
+        mock_envoy_filter
+            .expect_get_attribute_string()
+            .times(1)
+            .returning(|_| Some(EnvoyBuffer::new("127.0.0.1:80")));
+        mock_envoy_filter
+            .expect_get_attribute_int()
+            .times(1)
+            .returning(|_| Some(80));
+        mock_envoy_filter
+            .expect_send_response()
+            .times(1)
+            .returning(|code, _, _| assert!(code == 403));
+        mock_envoy_filter
+            .expect_increment_counter()
+            .times(1)
+            .returning(|_, _| {});
+
+        assert_eq!(
+            filter.on_request_headers(&mut mock_envoy_filter, true),
+            abi::envoy_dynamic_module_type_on_http_filter_request_headers_status::StopIteration
+        );
+    }
+
+    // Builds a deny-list-only `pb::FilterConfig` equivalent to the JSON
+    // `{"deny_addresses": ["192.168.1.1"]}`.
+    fn proto_config_denying_192_168_1_1() -> Vec<u8> {
+        let config = pb::FilterConfig {
+            deny_addresses: vec!["192.168.1.1".to_string()],
+            ..Default::default()
+        };
+        prost::Message::encode_to_vec(&config)
+    }
+
+    #[test]
+    fn test_from_proto_matches_equivalent_json_config() {
+        let json_config = FilterConfig::new(
+            r#"{
+        "deny_addresses": [
+          "192.168.1.1"
+        ]
+      }"#,
+        )
+        .unwrap();
+        let proto_config = FilterConfig::from_proto(&proto_config_denying_192_168_1_1()).unwrap();
+
+        for filter_config in [json_config, proto_config] {
+            let mut filter = Filter { filter_config };
+
+            let mut mock_envoy_filter =
+                envoy_proxy_dynamic_modules_rust_sdk::MockEnvoyHttpFilter::new();
+            mock_envoy_filter
+                .expect_get_attribute_string()
+                .times(1)
+                .returning(|_| Some(EnvoyBuffer::new("192.168.1.1:80")));
+            mock_envoy_filter
+                .expect_get_attribute_int()
+                .times(1)
+                .returning(|_| Some(80));
+            mock_envoy_filter
+                .expect_send_response()
+                .times(1)
+                .returning(|code, _, _| assert!(code == 403));
+            mock_envoy_filter
+                .expect_increment_counter()
+                .times(1)
+                .returning(|_, _| {});
+
+            assert_eq!(
+                filter.on_request_headers(&mut mock_envoy_filter, true),
+                abi::envoy_dynamic_module_type_on_http_filter_request_headers_status::StopIteration
+            );
+        }
+    }
+
+    #[test]
+    fn test_from_proto_rejects_malformed_bytes() {
+        assert!(FilterConfig::from_proto(b"not a valid protobuf message \xff\xfe").is_none());
+    }
+
+    #[test]
+    fn test_filter_records_dynamic_metadata_when_configured() {
+        let filter_config = FilterConfig::new(
+            r#"{
+        "deny_addresses": [
+          "192.168.1.1"
+        ],
+        "dynamic_metadata": {
+          "namespace": "envoy.filters.http.dynamic_modules.ip_restriction"
+        }
+      }"#,
+        );
+        assert!(filter_config.is_some());
+
+        let mut filter = Filter {
+            filter_config: filter_config.unwrap(),
+        };
+
+        let mut mock_envoy_filter =
+            envoy_proxy_dynamic_modules_rust_sdk::MockEnvoyHttpFilter::new();
+
+        mock_envoy_filter
+            .expect_get_attribute_string()
+            .times(1)
+            .returning(|_| Some(EnvoyBuffer::new("192.168.1.1:80")));
+        mock_envoy_filter
+            .expect_get_attribute_int()
+            .times(1)
+            .returning(|_| Some(80));
+        mock_envoy_filter
+            .expect_send_response()
+            .times(1)
+            .returning(|code, _, _| assert!(code == 403));
+        mock_envoy_filter
+            .expect_increment_counter()
+            .times(1)
+            .returning(|name, amount| {
+                assert_eq!(name, "ip_restriction.denied_denylist_hit");
+                assert_eq!(amount, 1);
+            });
+        mock_envoy_filter
+            .expect_set_dynamic_metadata_string()
+            .times(2)
+            .returning(|namespace, key, value| {
+                assert_eq!(namespace, "envoy.filters.http.dynamic_modules.ip_restriction");
+                match key {
+                    "client_address" => assert_eq!(value, "192.168.1.1"),
+                    "decision" => assert_eq!(value, "denied_denylist_hit"),
+                    other => panic!("unexpected dynamic metadata key: {other}"),
+                }
+            });
+
+        assert_eq!(
+            filter.on_request_headers(&mut mock_envoy_filter, true),
+            abi::envoy_dynamic_module_type_on_http_filter_request_headers_status::StopIteration
+        );
+    }
+
+    #[test]
+    fn test_filter_omits_dynamic_metadata_when_not_configured() {
+        let filter_config = FilterConfig::new(
+            r#"{
+        "deny_addresses": [
+          "192.168.1.1"
+        ]
+      }"#,
+        );
+        assert!(filter_config.is_some());
+
+        let mut filter = Filter {
+            filter_config: filter_config.unwrap(),
+        };
+
+        let mut mock_envoy_filter =
+            envoy_proxy_dynamic_modules_rust_sdk::MockEnvoyHttpFilter::new();
+
+        mock_envoy_filter
+            .expect_get_attribute_string()
+            .times(1)
+            .returning(|_| Some(EnvoyBuffer::new("192.168.1.1:80")));
+        mock_envoy_filter
+            .expect_get_attribute_int()
+            .times(1)
+            .returning(|_| Some(80));
+        mock_envoy_filter
+            .expect_send_response()
+            .times(1)
+            .returning(|code, _, _| assert!(code == 403));
+        mock_envoy_filter
+            .expect_increment_counter()
+            .times(1)
+            .returning(|_, _| {});
+
+        // No `expect_set_dynamic_metadata_string()` is registered: the mock
+        // panics on an unexpected call, so this also proves metadata is only
+        // emitted when `dynamic_metadata` is configured.
+        assert_eq!(
+            filter.on_request_headers(&mut mock_envoy_filter, true),
+            abi::envoy_dynamic_module_type_on_http_filter_request_headers_status::StopIteration
+        );
+    }
 }
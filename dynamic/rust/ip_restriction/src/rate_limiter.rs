@@ -0,0 +1,131 @@
+// A per-client-IP token-bucket rate limiter. The bucket map is sharded by a
+// hash of the address so concurrent requests from different clients don't
+// contend on a single lock, while requests from the same client are
+// serialized through the bucket they share.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+const NUM_SHARDS: usize = 16;
+
+// Caps the number of buckets tracked per shard, so a client that can send
+// requests from unique, unbounded addresses (e.g. via spoofed `x-forwarded-
+// for` entries when `use_xff` is set) can't grow this map without bound and
+// exhaust memory. Once a shard is full, the least-recently-refilled bucket
+// is evicted to make room for a new address.
+const MAX_BUCKETS_PER_SHARD: usize = 4096;
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+#[derive(Debug)]
+pub struct RateLimiter {
+    shards: Vec<Mutex<HashMap<IpAddr, Bucket>>>,
+    requests_per_unit: u32,
+    fill_interval: Duration,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_unit: u32, fill_interval: Duration) -> Self {
+        Self {
+            shards: (0..NUM_SHARDS)
+                .map(|_| Mutex::new(HashMap::new()))
+                .collect(),
+            requests_per_unit,
+            fill_interval,
+        }
+    }
+
+    fn shard_for(&self, addr: &IpAddr) -> &Mutex<HashMap<IpAddr, Bucket>> {
+        let mut hasher = DefaultHasher::new();
+        addr.hash(&mut hasher);
+        &self.shards[(hasher.finish() as usize) % self.shards.len()]
+    }
+
+    /// Lazily refills `addr`'s bucket based on elapsed time, then consumes a
+    /// token if one is available. Returns `true` if the request is allowed.
+    pub fn allow(&self, addr: IpAddr) -> bool {
+        let capacity = self.requests_per_unit as f64;
+        let now = Instant::now();
+
+        let mut shard = self.shard_for(&addr).lock().unwrap();
+        if !shard.contains_key(&addr) && shard.len() >= MAX_BUCKETS_PER_SHARD {
+            if let Some(&stalest) = shard
+                .iter()
+                .min_by_key(|(_, bucket)| bucket.last_refill)
+                .map(|(addr, _)| addr)
+            {
+                shard.remove(&stalest);
+            }
+        }
+        let bucket = shard.entry(addr).or_insert_with(|| Bucket {
+            tokens: capacity,
+            last_refill: now,
+        });
+
+        let elapsed = now.saturating_duration_since(bucket.last_refill);
+        if !elapsed.is_zero() {
+            let refill = elapsed.as_secs_f64() / self.fill_interval.as_secs_f64() * capacity;
+            bucket.tokens = (bucket.tokens + refill).min(capacity);
+            bucket.last_refill = now;
+        }
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allows_up_to_capacity_then_denies() {
+        let limiter = RateLimiter::new(2, Duration::from_secs(60));
+        let addr = IpAddr::from([127, 0, 0, 1]);
+
+        assert!(limiter.allow(addr));
+        assert!(limiter.allow(addr));
+        assert!(!limiter.allow(addr));
+    }
+
+    #[test]
+    fn test_bucket_count_is_bounded_per_shard() {
+        let limiter = RateLimiter::new(1, Duration::from_secs(60));
+
+        // Flood the limiter with twice as many distinct addresses as all
+        // shards together may hold; each shard must stay capped rather than
+        // growing without bound.
+        for i in 0..(NUM_SHARDS * MAX_BUCKETS_PER_SHARD * 2) as u32 {
+            limiter.allow(IpAddr::from(i.to_be_bytes()));
+        }
+
+        for shard in &limiter.shards {
+            assert!(shard.lock().unwrap().len() <= MAX_BUCKETS_PER_SHARD);
+        }
+    }
+
+    #[test]
+    fn test_buckets_are_independent_per_address() {
+        let limiter = RateLimiter::new(1, Duration::from_secs(60));
+        let a = IpAddr::from([127, 0, 0, 1]);
+        let b = IpAddr::from([127, 0, 0, 2]);
+
+        assert!(limiter.allow(a));
+        assert!(!limiter.allow(a));
+        assert!(limiter.allow(b));
+    }
+}
@@ -0,0 +1,109 @@
+// A minimal binary (radix) trie used for longest-prefix-match lookups over
+// IP address ranges. Addresses are represented as a `u128` with the address
+// left-aligned to the most significant bit (an IPv4 address occupies the top
+// 32 bits) and inserted bit-by-bit from the most significant bit down to
+// `prefix_len` bits.
+//
+// Lookups only need a yes/no answer (is this address covered by *any*
+// configured prefix), so we short-circuit as soon as we pass through a node
+// that terminates a configured prefix, rather than tracking the single
+// longest match.
+
+#[derive(Debug, Default)]
+struct TrieNode {
+    children: [Option<Box<TrieNode>>; 2],
+    // True if a configured prefix ends exactly at this node.
+    terminal: bool,
+}
+
+/// A prefix trie keyed on the top `MAX_BITS` bits of a `u128`.
+///
+/// `MAX_BITS` is `32` for IPv4 and `128` for IPv6; the key is always passed
+/// in as a `u128` with the address left-aligned to the most significant bit.
+#[derive(Debug, Default)]
+pub struct PrefixTrie<const MAX_BITS: u32> {
+    root: TrieNode,
+}
+
+impl<const MAX_BITS: u32> PrefixTrie<MAX_BITS> {
+    /// Inserts `key` (left-aligned within `MAX_BITS`) as a `/prefix_len` range.
+    pub fn insert(&mut self, key: u128, prefix_len: u8) {
+        let prefix_len = prefix_len.min(MAX_BITS as u8);
+        let mut node = &mut self.root;
+        for i in 0..prefix_len {
+            let bit = bit_at(key, i);
+            node = node.children[bit as usize].get_or_insert_with(Box::default);
+        }
+        node.terminal = true;
+    }
+
+    /// Returns true if `key` falls under any previously inserted prefix.
+    pub fn longest_prefix_match(&self, key: u128) -> bool {
+        let mut node = &self.root;
+        if node.terminal {
+            return true;
+        }
+        for i in 0..MAX_BITS as u8 {
+            let bit = bit_at(key, i);
+            match &node.children[bit as usize] {
+                Some(next) => {
+                    node = next;
+                    if node.terminal {
+                        return true;
+                    }
+                }
+                None => return false,
+            }
+        }
+        false
+    }
+}
+
+// Returns the `i`-th bit of `key` counting from the most significant bit,
+// treating `key` as left-aligned within `MAX_BITS` (i.e. bit 0 is the
+// topmost bit of the 128-bit word).
+fn bit_at(key: u128, i: u8) -> u8 {
+    ((key >> (127 - i as u32)) & 1) as u8
+}
+
+pub type Ipv4Trie = PrefixTrie<32>;
+pub type Ipv6Trie = PrefixTrie<128>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ipv4_exact_prefix() {
+        let mut trie = Ipv4Trie::default();
+        trie.insert((10u32 as u128) << 96, 32);
+        assert!(trie.longest_prefix_match((10u32 as u128) << 96));
+        assert!(!trie.longest_prefix_match((11u32 as u128) << 96));
+    }
+
+    #[test]
+    fn test_ipv4_range_prefix() {
+        let mut trie = Ipv4Trie::default();
+        // 10.0.0.0/8
+        let network: u32 = 10 << 24;
+        trie.insert((network as u128) << 96, 8);
+
+        let inside: u32 = (10 << 24) | 1;
+        let outside: u32 = 11 << 24;
+        assert!(trie.longest_prefix_match((inside as u128) << 96));
+        assert!(!trie.longest_prefix_match((outside as u128) << 96));
+    }
+
+    #[test]
+    fn test_ipv6_range_prefix() {
+        let mut trie = Ipv6Trie::default();
+        // 2001:db8::/32
+        let network: u128 = 0x2001_0db8_0000_0000_0000_0000_0000_0000;
+        trie.insert(network, 32);
+
+        let inside: u128 = 0x2001_0db8_0000_0000_0000_0000_0000_0001;
+        let outside: u128 = 0x2001_0db9_0000_0000_0000_0000_0000_0000;
+        assert!(trie.longest_prefix_match(inside));
+        assert!(!trie.longest_prefix_match(outside));
+    }
+}